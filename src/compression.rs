@@ -0,0 +1,93 @@
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+const SECTOR_SIZE: u64 = 4096;
+
+/// Set on the in-region compression byte when the chunk's payload lives in a
+/// sibling `.mcc` file instead of the region body (chunks too large to fit
+/// in the `MAX_INLINE_SECTORS` sectors `num_sectors` can address).
+pub const EXTERNAL_FLAG: u8 = 0x80;
+/// `num_sectors` in a region file's location table is a single byte, so an inline
+/// chunk (header + payload) can occupy at most 255 sectors before that field
+/// overflows into the offset bits of the packed location entry.
+pub const MAX_INLINE_SECTORS: u64 = 255;
+
+/// Whether a chunk payload this large has to be written to an external `.mcc`
+/// file rather than inline, because it wouldn't fit in the 255 sectors
+/// `num_sectors` can address.
+pub fn exceeds_inline_capacity(data_len: usize) -> bool {
+    // The payload is preceded by a 4-byte length and a 1-byte compression type.
+    data_len as u64 + 5 > MAX_INLINE_SECTORS * SECTOR_SIZE
+}
+
+/// The four compression methods the Anvil format can declare for a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    Gzip,
+    Zlib,
+    Uncompressed,
+    Lz4,
+}
+
+impl CompressionMethod {
+    pub fn from_byte(compression_type: u8) -> std::io::Result<Self> {
+        match compression_type & !EXTERNAL_FLAG {
+            1 => Ok(CompressionMethod::Gzip),
+            2 => Ok(CompressionMethod::Zlib),
+            3 => Ok(CompressionMethod::Uncompressed),
+            4 => Ok(CompressionMethod::Lz4),
+            other => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown chunk data compression method: {other}"),
+            )),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            CompressionMethod::Gzip => 1,
+            CompressionMethod::Zlib => 2,
+            CompressionMethod::Uncompressed => 3,
+            CompressionMethod::Lz4 => 4,
+        }
+    }
+}
+
+pub fn is_external(compression_type: u8) -> bool {
+    compression_type & EXTERNAL_FLAG != 0
+}
+
+/// Decompresses a chunk payload according to its declared compression method.
+/// `compression_type` may have the external-file bit set; it is ignored here.
+pub fn decompress(compression_type: u8, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let method = CompressionMethod::from_byte(compression_type)?;
+    let mut out = Vec::new();
+    match method {
+        CompressionMethod::Gzip => {
+            GzDecoder::new(Cursor::new(data)).read_to_end(&mut out)?;
+        }
+        CompressionMethod::Zlib => {
+            ZlibDecoder::new(Cursor::new(data)).read_to_end(&mut out)?;
+        }
+        CompressionMethod::Uncompressed => {
+            out.extend_from_slice(data);
+        }
+        CompressionMethod::Lz4 => {
+            out = lz4_flex::block::decompress_size_prepended(data).map_err(|err| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("lz4 decode failed: {err}"))
+            })?;
+        }
+    }
+    Ok(out)
+}
+
+/// Path of the external `.mcc` file holding an oversized chunk's payload, keyed
+/// by the chunk's absolute coordinates. Vanilla/Paper use a `c.` prefix here
+/// (unlike the region file's own `r.` prefix), so this has to match or the
+/// game won't find chunks thanos-rs writes externally, and thanos-rs won't
+/// find `.mcc` files the game already wrote.
+pub fn mcc_path(region_dir: &Path, chunk_x: i32, chunk_z: i32) -> PathBuf {
+    region_dir.join(format!("c.{chunk_x}.{chunk_z}.mcc"))
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Mutex;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::compression::CompressionMethod;
+
+/// Hash of every decompressed chunk payload seen so far, mapped to the number
+/// of times it's been seen. Hashing the decompressed NBT (rather than the
+/// compressed bytes) means two chunks that recompress differently still count
+/// as the same payload.
+pub type SeenChunkHashes = Mutex<HashMap<u64, u64>>;
+
+pub fn new_seen_chunk_hashes() -> SeenChunkHashes {
+    Mutex::new(HashMap::new())
+}
+
+/// Fast non-cryptographic hash of a decompressed chunk's NBT bytes. Uses a real
+/// 64-bit hash (xxh3) rather than a 32-bit one, since worlds can hold millions
+/// of kept chunks and a 32-bit hash starts colliding well within that range.
+pub fn hash_chunk(decompressed: &[u8]) -> u64 {
+    xxhash_rust::xxh3::xxh3_64(decompressed)
+}
+
+/// Records a chunk's hash, returning `true` if this exact payload was already seen.
+pub fn record_and_check_duplicate(seen: &SeenChunkHashes, hash: u64) -> bool {
+    let mut seen = seen.lock().unwrap();
+    let count = seen.entry(hash).or_insert(0);
+    *count += 1;
+    *count > 1
+}
+
+/// Recompresses a duplicate-candidate chunk's decompressed payload with zlib's
+/// best compression level, and returns it only if it's smaller than the chunk's
+/// current on-disk payload. Vanilla Minecraft only understands gzip/zlib/raw/lz4,
+/// so - unlike a cryptographic hash - we don't invent a new compression type
+/// (e.g. zstd) that would make the world unreadable by the game.
+pub fn recompress_if_smaller(decompressed: &[u8], current_compression_byte: u8, current_data: &[u8]) -> Option<(u8, Vec<u8>)> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(decompressed).ok()?;
+    let recompressed = encoder.finish().ok()?;
+
+    if recompressed.len() < current_data.len() {
+        Some((CompressionMethod::Zlib.to_byte() | (current_compression_byte & crate::compression::EXTERNAL_FLAG), recompressed))
+    } else {
+        None
+    }
+}
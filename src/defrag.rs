@@ -0,0 +1,124 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use crate::stats::OptimisationStats;
+
+const SECTOR_SIZE: u64 = 4096;
+
+struct Entry {
+    index: usize,
+    sector_offset: u32,
+    num_sectors: u32,
+}
+
+/// Shifts a region file's chunks backward to close sector gaps, without reading
+/// or rewriting the chunks that don't need to move. Chunk data, and the
+/// timestamp table, are left untouched; only the location-table offsets change.
+/// Safe to run repeatedly - an already-defragmented file is a no-op.
+///
+/// A location entry that doesn't fit in the file, or overlaps another entry, is
+/// zeroed (dropped, like `--repair`'s in-place mode) instead of aborting the
+/// whole file - the same bounds/overlap checks `region::read_chunk_payload`
+/// uses. Leaving such an entry's stale offset in the table instead would risk
+/// it later colliding with a valid chunk shifted into its old sectors.
+///
+/// With `dry_run`, only computes the before/after sizes that would result -
+/// the file is opened read-only and nothing on disk is touched.
+pub fn defragment_region_file(path: &Path, dry_run: bool) -> std::io::Result<(u64, u64)> {
+    let mut file = OpenOptions::new().read(true).write(!dry_run).open(path)?;
+    let file_len = file.metadata()?.len();
+    if file_len < 2 * SECTOR_SIZE {
+        return Ok((file_len, file_len));
+    }
+
+    let mut location_table = vec![0u8; SECTOR_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut location_table)?;
+
+    let mut entries = Vec::new();
+    let mut occupied: Vec<(u32, u32)> = Vec::new();
+    for index in 0..1024 {
+        let loc = i32::from_be_bytes([
+            location_table[index * 4],
+            location_table[index * 4 + 1],
+            location_table[index * 4 + 2],
+            location_table[index * 4 + 3],
+        ]);
+        let num_sectors = (loc & 0xFF) as u32;
+        let sector_offset = (loc >> 8) as u32;
+        if sector_offset == 0 && num_sectors == 0 {
+            continue;
+        }
+
+        let out_of_bounds = sector_offset < 2 || (sector_offset as u64 + num_sectors as u64) * SECTOR_SIZE > file_len;
+        let overlapping = occupied
+            .iter()
+            .any(|&(other_offset, other_len)| sector_offset < other_offset + other_len && other_offset < sector_offset + num_sectors);
+        if out_of_bounds || overlapping {
+            eprintln!(
+                "Corrupt chunk location at index {index} in {}: {}, dropping it",
+                path.display(),
+                if out_of_bounds { "out of bounds" } else { "overlapping" },
+            );
+            if !dry_run {
+                location_table[index * 4..index * 4 + 4].copy_from_slice(&[0u8; 4]);
+            }
+            continue;
+        }
+
+        occupied.push((sector_offset, num_sectors));
+        entries.push(Entry { index, sector_offset, num_sectors });
+    }
+    entries.sort_by_key(|entry| entry.sector_offset);
+
+    let mut next_free_sector: u32 = 2;
+    for entry in &entries {
+        if entry.sector_offset > next_free_sector && !dry_run {
+            let mut sectors = vec![0u8; entry.num_sectors as usize * SECTOR_SIZE as usize];
+            file.seek(SeekFrom::Start(entry.sector_offset as u64 * SECTOR_SIZE))?;
+            file.read_exact(&mut sectors)?;
+            file.seek(SeekFrom::Start(next_free_sector as u64 * SECTOR_SIZE))?;
+            file.write_all(&sectors)?;
+
+            let new_loc = ((next_free_sector as i32) << 8) | entry.num_sectors as i32;
+            location_table[entry.index * 4..entry.index * 4 + 4].copy_from_slice(&new_loc.to_be_bytes());
+        }
+        next_free_sector += entry.num_sectors;
+    }
+
+    let final_len = next_free_sector as u64 * SECTOR_SIZE;
+
+    if !dry_run {
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&location_table)?;
+        file.set_len(final_len)?;
+    }
+
+    Ok((file_len, final_len))
+}
+
+/// Runs in-place defragmentation over every `.mca` file in `region_dir`.
+pub fn defragment_region_directory(region_dir: &str, stats: &OptimisationStats, dry_run: bool) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(region_dir)?.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if !file_name.ends_with(".mca") {
+            continue;
+        }
+
+        match defragment_region_file(&path, dry_run) {
+            Ok((before, after)) => {
+                stats.region_files_processed.fetch_add(1, Ordering::Relaxed);
+                stats.bytes_reclaimed.fetch_add(before as i64 - after as i64, Ordering::Relaxed);
+            }
+            Err(err) => eprintln!("error: failed to defragment '{}': {}", path.display(), err),
+        }
+    }
+
+    Ok(())
+}
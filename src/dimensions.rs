@@ -0,0 +1,64 @@
+use std::path::{Path, PathBuf};
+
+/// Directory names that hold Anvil region files somewhere under a world directory.
+pub const REGION_BEARING_DIRS: [&str; 3] = ["region", "entities", "poi"];
+
+/// A region-bearing folder discovered under a world directory, given as a path
+/// relative to the world root (e.g. `region`, `DIM-1/poi`, `dimensions/mymod/void/region`).
+#[derive(Debug, Clone)]
+pub struct RegionFolder {
+    pub relative_path: PathBuf,
+}
+
+impl RegionFolder {
+    /// Whether `InhabitedTime`-based pruning applies here. `entities` and `poi`
+    /// folders share the Anvil container format but store different NBT, so they
+    /// are copied through untouched rather than pruned.
+    pub fn supports_inhabited_time_pruning(&self) -> bool {
+        self.relative_path.file_name().and_then(|n| n.to_str()) == Some("region")
+    }
+}
+
+/// Finds every region-bearing folder in a world: the overworld `region/`,
+/// `DIM-1/` (the Nether) and `DIM1/` (the End), their sibling `entities/` and
+/// `poi/` folders, and any `dimensions/<namespace>/<name>/` custom dimension.
+pub fn discover_region_folders(world_dir: &Path) -> Vec<RegionFolder> {
+    let mut dimension_roots = vec![PathBuf::new()];
+
+    for dim in ["DIM-1", "DIM1"] {
+        if world_dir.join(dim).is_dir() {
+            dimension_roots.push(PathBuf::from(dim));
+        }
+    }
+
+    let dimensions_dir = world_dir.join("dimensions");
+    if let Ok(namespaces) = std::fs::read_dir(&dimensions_dir) {
+        for namespace_entry in namespaces.filter_map(Result::ok) {
+            if !namespace_entry.path().is_dir() {
+                continue;
+            }
+            let names_dir = namespace_entry.path();
+            if let Ok(names) = std::fs::read_dir(&names_dir) {
+                for name_entry in names.filter_map(Result::ok) {
+                    if name_entry.path().is_dir() {
+                        dimension_roots.push(
+                            Path::new("dimensions")
+                                .join(namespace_entry.file_name())
+                                .join(name_entry.file_name()),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    let mut folders = Vec::new();
+    for root in dimension_roots {
+        for dir_name in REGION_BEARING_DIRS {
+            if world_dir.join(&root).join(dir_name).is_dir() {
+                folders.push(RegionFolder { relative_path: root.join(dir_name) });
+            }
+        }
+    }
+    folders
+}
@@ -1,14 +1,25 @@
+mod compression;
+mod dedupe;
+mod defrag;
+mod dimensions;
+mod region;
+mod scan;
+mod stats;
 mod utils;
 
+use crate::dedupe::SeenChunkHashes;
+use crate::defrag::defragment_region_directory;
+use crate::dimensions::discover_region_folders;
+use crate::region::{ChunkLocation, KeptChunk, SECTOR_SIZE};
+use crate::scan::scan_region_directory;
+use crate::stats::OptimisationStats;
 use crate::utils::copy_except_region;
-use clap::{Arg, Command};
-use flate2::read::GzDecoder;
-use flate2::read::ZlibDecoder;
+use clap::{Arg, ArgAction, Command};
 use rayon::prelude::*;
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::path::Path;
-
-const SECTOR_SIZE: u64 = 4096;
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() {
     let cmd = Command::new("thanos-rs")
@@ -27,13 +38,64 @@ fn main() {
             .default_value("4")
             .required(false)
             .short('t')
-            .help("Specify the number of threads to use for processing"));
+            .help("Specify the number of threads to use for processing"))
+        .arg(Arg::new("scan")
+            .long("scan")
+            .action(ArgAction::SetTrue)
+            .help("Validate every chunk entry and print a corruption summary instead of optimising"))
+        .arg(Arg::new("repair")
+            .long("repair")
+            .action(ArgAction::SetTrue)
+            .help("Like --scan, but also drops corrupt chunks (skipped in copy mode, zeroed in-place)"))
+        .arg(Arg::new("dry-run")
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("Compute the full optimisation report without writing any output"))
+        .arg(Arg::new("older-than")
+            .long("older-than")
+            .required(false)
+            .help("Also delete chunks last modified more than this many seconds ago"))
+        .arg(Arg::new("require-both")
+            .long("require-both")
+            .action(ArgAction::SetTrue)
+            .requires("older-than")
+            .help("With --older-than, only delete a chunk when both the age and InhabitedTime criteria are met (default: either)"))
+        .arg(Arg::new("defrag")
+            .long("defrag")
+            .action(ArgAction::SetTrue)
+            .help("In-place only: shift chunks to close sector gaps instead of doing a full rewrite"))
+        .arg(Arg::new("dedupe")
+            .long("dedupe")
+            .action(ArgAction::SetTrue)
+            .help("Hash kept chunks' decompressed payload and report byte-identical duplicates"))
+        .arg(Arg::new("recompress")
+            .long("recompress")
+            .action(ArgAction::SetTrue)
+            .requires("dedupe")
+            .help("With --dedupe, recompress duplicate chunks at a stronger zlib level if that shrinks them"));
     let matches = cmd.get_matches();
 
     let input_dir = matches.get_one::<String>("input_dir").unwrap();
     let output_dir = matches.get_one::<String>("output_dir").unwrap_or(input_dir);
     let inhabited_time_threshold: i64 = matches.get_one::<String>("inhabited-time").unwrap().parse().unwrap();
     let num_threads: usize = matches.get_one::<String>("threads").unwrap().parse().unwrap();
+    let scan = matches.get_flag("scan");
+    let repair = matches.get_flag("repair");
+    let dry_run = matches.get_flag("dry-run");
+    let older_than_seconds: Option<u64> = matches.get_one::<String>("older-than").map(|s| s.parse().unwrap());
+    let require_both = matches.get_flag("require-both");
+    let older_than_cutoff: Option<i64> = older_than_seconds.map(|secs| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        now - secs as i64
+    });
+    let defrag = matches.get_flag("defrag");
+    let dedupe = matches.get_flag("dedupe");
+    let recompress = matches.get_flag("recompress");
+
+    if defrag && input_dir != output_dir {
+        eprintln!("error: --defrag only works when input and output directories are the same.");
+        std::process::exit(1);
+    }
 
     rayon::ThreadPoolBuilder::new().num_threads(num_threads).build_global().unwrap();
 
@@ -42,7 +104,7 @@ fn main() {
         std::process::exit(1);
     }
 
-    if input_dir != output_dir {
+    if input_dir != output_dir && !dry_run {
         if Path::new(output_dir).exists() {
             eprintln!("error: output directory already exists.");
             std::process::exit(1);
@@ -59,23 +121,102 @@ fn main() {
             std::process::exit(1);
         });
         println!("Copied world files");
+    }
+
+    let region_folders = discover_region_folders(Path::new(input_dir));
+    if region_folders.is_empty() {
+        eprintln!("error: no region-bearing folders (region/entities/poi) found under '{}'.", input_dir);
+        std::process::exit(1);
+    }
+
+    let stats = OptimisationStats::new();
+    let seen_chunk_hashes = dedupe::new_seen_chunk_hashes();
 
-        std::fs::create_dir(format!("{}/region", output_dir)).unwrap_or_else(|err| {
-            eprintln!("error: couldn't create region directory - {}", err);
+    for folder in &region_folders {
+        let input_folder = Path::new(input_dir).join(&folder.relative_path);
+        let output_folder = Path::new(output_dir).join(&folder.relative_path);
+
+        if input_dir != output_dir && !dry_run {
+            std::fs::create_dir_all(&output_folder).unwrap_or_else(|err| {
+                eprintln!("error: couldn't create '{}' - {}", output_folder.display(), err);
+                std::process::exit(1);
+            });
+        }
+
+        if scan || repair {
+            let summary = scan_region_directory(
+                input_folder.to_str().unwrap(),
+                output_folder.to_str().unwrap(),
+                repair,
+                dry_run,
+            ).unwrap_or_else(|err| {
+                eprintln!("error: scan failed - {}", err);
+                std::process::exit(1);
+            });
+            println!("{}:", folder.relative_path.display());
+            summary.print_report();
+            continue;
+        }
+
+        if defrag {
+            defragment_region_directory(input_folder.to_str().unwrap(), &stats, dry_run).unwrap_or_else(|err| {
+                eprintln!("error: failed to defragment '{}' - {}", folder.relative_path.display(), err);
+                std::process::exit(1);
+            });
+            continue;
+        }
+
+        let options = OptimiseOptions {
+            inhabited_time_threshold,
+            filter_by_inhabited_time: folder.supports_inhabited_time_pruning(),
+            dry_run,
+            older_than_cutoff,
+            require_both,
+            dedupe,
+            recompress,
+            seen_chunk_hashes: &seen_chunk_hashes,
+        };
+        optimise_region_files(input_folder.to_str().unwrap(), output_folder.to_str().unwrap(), &stats, &options).unwrap_or_else(|err| {
+            eprintln!("error: failed to optimise '{}' - {}", folder.relative_path.display(), err);
             std::process::exit(1);
         });
     }
 
-    // TODO: Detect other Dimensions
-    optimise_region_files(format!("{input_dir}/region").as_str(), format!("{output_dir}/region").as_str(), inhabited_time_threshold).expect("TODO: panic message");
+    if !scan && !repair {
+        stats.print_report(dry_run);
+    }
 }
 
 
-fn optimise_region_files(input_directory: &str, output_directory: &str, inhabited_time_threshold: i64) -> std::io::Result<()> {
+/// Knobs controlling how `optimise_region_files` decides what to keep, bundled
+/// into one struct because the list kept growing a parameter at a time.
+struct OptimiseOptions<'a> {
+    inhabited_time_threshold: i64,
+    filter_by_inhabited_time: bool,
+    dry_run: bool,
+    older_than_cutoff: Option<i64>,
+    require_both: bool,
+    dedupe: bool,
+    recompress: bool,
+    seen_chunk_hashes: &'a SeenChunkHashes,
+}
+
+fn optimise_region_files(
+    input_directory: &str,
+    output_directory: &str,
+    stats: &OptimisationStats,
+    options: &OptimiseOptions,
+) -> std::io::Result<()> {
     let input_directory = Path::new(input_directory);
     let output_directory = Path::new(output_directory);
 
-    let equal_input_output = input_directory.canonicalize()? == output_directory.canonicalize()?;
+    // `--dry-run` against a new destination never creates `output_directory`, so it may
+    // not exist yet; canonicalize() would fail on it even though it's obviously not the
+    // same directory as `input_directory`.
+    let equal_input_output = match (input_directory.canonicalize(), output_directory.canonicalize()) {
+        (Ok(input_canonical), Ok(output_canonical)) => input_canonical == output_canonical,
+        _ => false,
+    };
 
     let input_files: Vec<_> = std::fs::read_dir(input_directory)?
         .filter_map(Result::ok)
@@ -95,11 +236,30 @@ fn optimise_region_files(input_directory: &str, output_directory: &str, inhabite
             return;
         }
 
-        let region_x = parts[1].parse::<i32>().unwrap();
-        let region_z = parts[2].parse::<i32>().unwrap();
+        let (region_x, region_z) = match (parts[1].parse::<i32>(), parts[2].parse::<i32>()) {
+            (Ok(x), Ok(z)) => (x, z),
+            _ => return,
+        };
+
+        let mut file = match std::fs::File::open(&file_path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Error opening region file {}: {}", file_name, err);
+                return;
+            }
+        };
+        let file_len = match file.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(err) => {
+                eprintln!("Error reading metadata for region file {}: {}", file_name, err);
+                return;
+            }
+        };
 
-        let mut file = std::fs::File::open(file_path.clone()).unwrap();
-        let file_len = file.metadata().unwrap().len();
+        // `--dry-run` writes nothing, so empty region files are left in place.
+        if file_len == 0 && options.dry_run {
+            return;
+        }
 
         // Skipping/Removing empty region files
         if file_len == 0 {
@@ -120,10 +280,21 @@ fn optimise_region_files(input_directory: &str, output_directory: &str, inhabite
         }
 
         let mut location_table = vec![0; SECTOR_SIZE as usize];
-        file.seek(SeekFrom::Start(0)).unwrap();
-        file.read_exact(&mut location_table).unwrap();
+        let mut timestamp_table = vec![0; SECTOR_SIZE as usize];
+        let tables_read = file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| file.read_exact(&mut location_table))
+            .and_then(|_| file.seek(SeekFrom::Start(SECTOR_SIZE)))
+            .and_then(|_| file.read_exact(&mut timestamp_table));
+        if let Err(err) = tables_read {
+            eprintln!("Error reading header tables for region file {}: {}", file_name, err);
+            return;
+        }
 
-        let mut chunk_data = Vec::new();
+        stats.region_files_processed.fetch_add(1, Ordering::Relaxed);
+
+        let mut chunk_data: Vec<KeptChunk> = Vec::new();
+        let mut occupied: Vec<(u32, u32)> = Vec::new();
 
         // A region is made up of chunks in a 32 by 32 area.
         for x in 0..32 {
@@ -138,83 +309,132 @@ fn optimise_region_files(input_directory: &str, output_directory: &str, inhabite
                     continue; // Skips not generated chunks
                 }
 
-                // Seek to the position where the chunk header is located
-                // The size of the header is 5 bytes and followed by the stored chunk data
-                file.seek(SeekFrom::Start((sector_offset as u64) * SECTOR_SIZE)).unwrap();
-
-                let mut chunk_size = [0; 4]; // Chunk data size is specified in the first 4 bytes
-                file.read_exact(&mut chunk_size).unwrap();
-                let chunk_size = i32::from_be_bytes(chunk_size);
-
-                let mut compression_type = [0; 1]; // Compression type is specified in the last byte
-                file.read_exact(&mut compression_type).unwrap();
-                let compression_type = compression_type[0];
-
-                // Reads the chunk data with the calculated chunk size
-                let mut data = vec![0; chunk_size as usize - 1];
-                file.read_exact(&mut data).unwrap();
-
-                // TODO: Unused, maybe useful for Debugging Outputs?
-                let _chunk_x = region_x * 32 + x as i32;
-                let _chunk_z = region_z * 32 + z as i32;
+                stats.chunks_examined.fetch_add(1, Ordering::Relaxed);
+
+                let timestamp = i32::from_be_bytes([
+                    timestamp_table[index * 4],
+                    timestamp_table[index * 4 + 1],
+                    timestamp_table[index * 4 + 2],
+                    timestamp_table[index * 4 + 3],
+                ]);
+
+                let chunk_x = region_x * 32 + x as i32;
+                let chunk_z = region_z * 32 + z as i32;
+
+                // Validates the location-table entry before trusting any of it, so a
+                // single corrupt chunk (pointing outside the file, overlapping another
+                // chunk, or declaring a bogus size) is skipped instead of panicking.
+                let location = ChunkLocation { chunk_x, chunk_z, sector_offset: sector_offset as u32, num_sectors: num_sectors as u32 };
+                let (compression_byte, data) = match region::read_chunk_payload(&mut file, input_directory, file_len, &location, &mut occupied) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        eprintln!("Corrupt chunk ({chunk_x}, {chunk_z}) in {file_name}: {}", err.label());
+                        stats.chunks_deleted.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
 
-                if compression_type != 1 && compression_type != 2 {
-                    eprintln!("Error: unknown chunk data compression method: {}!", compression_type);
+                // `entities`/`poi` folders share the Anvil container but carry no InhabitedTime
+                // tag, so they're copied through untouched instead of pruned.
+                if !options.filter_by_inhabited_time {
+                    stats.chunks_kept.fetch_add(1, Ordering::Relaxed);
+                    chunk_data.push(KeptChunk { index, chunk_x, chunk_z, compression_byte, data, timestamp });
                     continue;
                 }
 
-                let mut decompressed_chunk_data: Vec<u8> = Vec::new();
-                match compression_type {
-                    1 => {
-                        let mut gz = GzDecoder::new(Cursor::new(data.clone()));
-                        if let Err(err) = gz.read_to_end(&mut decompressed_chunk_data) {
-                            eprintln!("Error decompressing chunk data: {}", err);
-                            continue;
+                let decompressed_chunk_data = match compression::decompress(compression_byte, &data) {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        eprintln!("Error decompressing chunk data: {}", err);
+                        stats.chunks_deleted.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+
+                let nbt = match simdnbt::borrow::read(&mut Cursor::new(&*decompressed_chunk_data)) {
+                    Ok(Some(nbt)) => nbt,
+                    _ => {
+                        eprintln!("Error reading NBT for chunk ({chunk_x}, {chunk_z})");
+                        stats.chunks_deleted.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+                let inhabited_time = match nbt.long("InhabitedTime") {
+                    Some(value) => value,
+                    None => {
+                        eprintln!("Chunk ({chunk_x}, {chunk_z}) missing InhabitedTime tag");
+                        stats.chunks_deleted.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+
+                let stale_by_inhabited_time = inhabited_time <= options.inhabited_time_threshold;
+                let stale_by_age = options.older_than_cutoff.map(|cutoff| (timestamp as i64) < cutoff);
+                let should_delete = match stale_by_age {
+                    None => stale_by_inhabited_time,
+                    Some(stale) => {
+                        if options.require_both {
+                            stale_by_inhabited_time && stale
+                        } else {
+                            stale_by_inhabited_time || stale
                         }
                     }
-                    2 => {
-                        let mut zlib = ZlibDecoder::new(Cursor::new(data.clone()));
-                        if let Err(err) = zlib.read_to_end(&mut decompressed_chunk_data) {
-                            eprintln!("Error decompressing chunk data: {}", err);
-                            continue;
+                };
+
+                if should_delete {
+                    stats.chunks_deleted.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    stats.chunks_kept.fetch_add(1, Ordering::Relaxed);
+
+                    let mut kept_compression_byte = compression_byte;
+                    let mut kept_data = data;
+
+                    if options.dedupe {
+                        let hash = dedupe::hash_chunk(&decompressed_chunk_data);
+                        if dedupe::record_and_check_duplicate(options.seen_chunk_hashes, hash) {
+                            stats.duplicate_chunks.fetch_add(1, Ordering::Relaxed);
+                            stats.duplicate_bytes.fetch_add(kept_data.len() as u64, Ordering::Relaxed);
+
+                            if options.recompress {
+                                if let Some((new_byte, new_data)) =
+                                    dedupe::recompress_if_smaller(&decompressed_chunk_data, kept_compression_byte, &kept_data)
+                                {
+                                    stats.recompressed_chunks.fetch_add(1, Ordering::Relaxed);
+                                    stats.recompression_savings.fetch_add(kept_data.len() as i64 - new_data.len() as i64, Ordering::Relaxed);
+                                    kept_compression_byte = new_byte;
+                                    kept_data = new_data;
+                                }
+                            }
                         }
                     }
-                    _ => unreachable!(),
-                }
-
-                let nbt = simdnbt::borrow::read(&mut Cursor::new(&*decompressed_chunk_data)).expect("Failed to read chunk data").unwrap();
-                let inhabited_time = nbt.long("InhabitedTime").unwrap();
 
-                if inhabited_time > inhabited_time_threshold {
-                    chunk_data.push((loc, compression_type, data));
+                    chunk_data.push(KeptChunk { index, chunk_x, chunk_z, compression_byte: kept_compression_byte, data: kept_data, timestamp });
                 }
             }
         }
 
         if chunk_data.is_empty() {
+            stats.region_files_emptied.fetch_add(1, Ordering::Relaxed);
+            stats.bytes_reclaimed.fetch_add(file_len as i64, Ordering::Relaxed);
             // TODO: Make this message only shop up when debug output is active (implement logging?)
             // println!("Skipping region file {} as it has no chunks left after optimisation", file_name);
+            if !options.dry_run && equal_input_output {
+                std::fs::remove_file(file_entry.path()).unwrap();
+            }
             return;
         }
 
-        // TODO: Clean up the following code and add comments
-        let mut output_file = std::fs::File::create(format!("{}/{}", output_directory.display(), file_name)).unwrap();
-        let mut offset = 2 * SECTOR_SIZE;
-        for (loc, compression_type, data) in chunk_data {
-            let num_sectors = (data.len() as u64 + SECTOR_SIZE - 1) / SECTOR_SIZE;
-            let new_loc = (offset / SECTOR_SIZE) << 8 | num_sectors;
-            output_file.seek(SeekFrom::Start((loc & 0xFF) as u64 * 4)).unwrap();
-            output_file.write_all(&new_loc.to_be_bytes()).unwrap();
-
-            output_file.seek(SeekFrom::Start(offset)).unwrap();
-            output_file.write_all(&(data.len() as i32 + 1).to_be_bytes()).unwrap();
-            output_file.write_all(&[compression_type]).unwrap();
-            output_file.write_all(&data).unwrap();
-
-            offset += num_sectors * SECTOR_SIZE;
+        let final_len = region::planned_len(&chunk_data);
+        stats.bytes_reclaimed.fetch_add(file_len as i64 - final_len as i64, Ordering::Relaxed);
+
+        if options.dry_run {
+            return;
         }
 
-        output_file.set_len(offset).unwrap();
+        let output_path = output_directory.join(file_name);
+        if let Err(err) = region::write_region_file(&output_path, output_directory, &chunk_data) {
+            eprintln!("Error writing region file {}: {}", file_name, err);
+        }
     });
 
     Ok(())
@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::compression;
+
+pub const SECTOR_SIZE: u64 = 4096;
+
+/// Structural problems found while reading a chunk's location-table entry,
+/// before its payload is even decompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkReadError {
+    /// `sector_offset` or `sector_offset + num_sectors` falls outside the file.
+    OutOfBounds,
+    /// The chunk's sectors overlap an earlier chunk's sectors.
+    Overlapping,
+    /// The declared `chunk_size` doesn't fit inside the allocated sectors.
+    SizeMismatch,
+}
+
+impl ChunkReadError {
+    pub fn label(self) -> &'static str {
+        match self {
+            ChunkReadError::OutOfBounds => "out of bounds",
+            ChunkReadError::Overlapping => "overlapping",
+            ChunkReadError::SizeMismatch => "size mismatch",
+        }
+    }
+}
+
+/// Where a chunk's payload lives within a region file, per its location-table entry.
+pub struct ChunkLocation {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub sector_offset: u32,
+    pub num_sectors: u32,
+}
+
+/// Reads a chunk's raw (still-compressed) payload, validating that its location-table
+/// entry actually makes sense for this file before trusting any of it. A corrupt
+/// entry - pointing outside the file, overlapping another chunk, or declaring a
+/// `chunk_size` that doesn't fit its sectors - is reported instead of panicking.
+pub fn read_chunk_payload(
+    file: &mut File,
+    region_dir: &Path,
+    file_len: u64,
+    location: &ChunkLocation,
+    occupied: &mut Vec<(u32, u32)>,
+) -> Result<(u8, Vec<u8>), ChunkReadError> {
+    let &ChunkLocation { chunk_x, chunk_z, sector_offset, num_sectors } = location;
+
+    if sector_offset < 2 || (sector_offset as u64 + num_sectors as u64) * SECTOR_SIZE > file_len {
+        return Err(ChunkReadError::OutOfBounds);
+    }
+
+    for &(other_offset, other_len) in occupied.iter() {
+        if sector_offset < other_offset + other_len && other_offset < sector_offset + num_sectors {
+            return Err(ChunkReadError::Overlapping);
+        }
+    }
+
+    file.seek(SeekFrom::Start(sector_offset as u64 * SECTOR_SIZE))
+        .map_err(|_| ChunkReadError::OutOfBounds)?;
+    let mut chunk_size = [0u8; 4];
+    file.read_exact(&mut chunk_size).map_err(|_| ChunkReadError::OutOfBounds)?;
+    let chunk_size = i32::from_be_bytes(chunk_size);
+
+    if chunk_size < 1 || (chunk_size as u64 + 4) > num_sectors as u64 * SECTOR_SIZE {
+        return Err(ChunkReadError::SizeMismatch);
+    }
+
+    let mut compression_byte = [0u8; 1];
+    file.read_exact(&mut compression_byte).map_err(|_| ChunkReadError::SizeMismatch)?;
+    let compression_byte = compression_byte[0];
+
+    let data = if compression::is_external(compression_byte) {
+        std::fs::read(compression::mcc_path(region_dir, chunk_x, chunk_z)).map_err(|_| ChunkReadError::OutOfBounds)?
+    } else {
+        let mut inline = vec![0u8; chunk_size as usize - 1];
+        file.read_exact(&mut inline).map_err(|_| ChunkReadError::SizeMismatch)?;
+        inline
+    };
+
+    occupied.push((sector_offset, num_sectors));
+    Ok((compression_byte, data))
+}
+
+/// A chunk kept for (re)writing into a region file.
+pub struct KeptChunk {
+    pub index: usize,
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub compression_byte: u8,
+    pub data: Vec<u8>,
+    pub timestamp: i32,
+}
+
+/// On-disk size a region file containing just `chunks` would need, including the
+/// two header sectors, laid out the same way `write_region_file` writes it. Used
+/// to report byte savings, including under `--dry-run` where nothing is written.
+pub fn planned_len(chunks: &[KeptChunk]) -> u64 {
+    2 * SECTOR_SIZE
+        + chunks
+            .iter()
+            .map(|chunk| {
+                if compression::exceeds_inline_capacity(chunk.data.len()) {
+                    SECTOR_SIZE
+                } else {
+                    (chunk.data.len() as u64 + 5).div_ceil(SECTOR_SIZE) * SECTOR_SIZE
+                }
+            })
+            .sum::<u64>()
+}
+
+/// Writes a fresh region file containing just `chunks`, writing any oversized
+/// chunk's payload to a sibling `.mcc` file in `output_dir` instead of inline.
+/// Shared by the normal optimisation pass and `--repair`'s copy-mode rewrite.
+pub fn write_region_file(output_path: &Path, output_dir: &Path, chunks: &[KeptChunk]) -> std::io::Result<()> {
+    let mut output_file = File::create(output_path)?;
+    let mut offset = 2 * SECTOR_SIZE;
+
+    for chunk in chunks {
+        let base_method = chunk.compression_byte & !compression::EXTERNAL_FLAG;
+        let is_external = compression::exceeds_inline_capacity(chunk.data.len());
+        let stored_compression_byte = if is_external { base_method | compression::EXTERNAL_FLAG } else { base_method };
+        // Inline chunks need room for the 4-byte length and 1-byte compression type too.
+        let num_sectors = if is_external { 1 } else { (chunk.data.len() as u64 + 5).div_ceil(SECTOR_SIZE) };
+
+        let new_loc = (offset / SECTOR_SIZE) << 8 | num_sectors;
+        output_file.seek(SeekFrom::Start(chunk.index as u64 * 4))?;
+        output_file.write_all(&(new_loc as i32).to_be_bytes())?;
+
+        output_file.seek(SeekFrom::Start(SECTOR_SIZE + chunk.index as u64 * 4))?;
+        output_file.write_all(&chunk.timestamp.to_be_bytes())?;
+
+        output_file.seek(SeekFrom::Start(offset))?;
+        if is_external {
+            std::fs::write(compression::mcc_path(output_dir, chunk.chunk_x, chunk.chunk_z), &chunk.data)?;
+            output_file.write_all(&1i32.to_be_bytes())?;
+            output_file.write_all(&[stored_compression_byte])?;
+        } else {
+            output_file.write_all(&(chunk.data.len() as i32 + 1).to_be_bytes())?;
+            output_file.write_all(&[stored_compression_byte])?;
+            output_file.write_all(&chunk.data)?;
+        }
+
+        offset += num_sectors * SECTOR_SIZE;
+    }
+
+    output_file.set_len(offset)?;
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use simdnbt::borrow::read as read_nbt;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::compression;
+use crate::region::{self, ChunkLocation, KeptChunk, SECTOR_SIZE};
+
+/// Why a chunk entry was rejected during a scan/repair pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    /// `sector_offset` or `sector_offset + num_sectors` falls outside the file.
+    OutOfBounds,
+    /// The chunk's sectors overlap an earlier chunk's sectors.
+    Overlapping,
+    /// The declared `chunk_size` doesn't fit inside the allocated sectors.
+    SizeMismatch,
+    /// The compressed payload failed to decompress.
+    DecompressionFailed,
+    /// The decompressed payload isn't valid NBT, or is missing a required tag.
+    MissingTags,
+}
+
+impl Corruption {
+    fn label(self) -> &'static str {
+        match self {
+            Corruption::OutOfBounds => "out of bounds",
+            Corruption::Overlapping => "overlapping",
+            Corruption::SizeMismatch => "size mismatch",
+            Corruption::DecompressionFailed => "undecompressable",
+            Corruption::MissingTags => "missing required tags",
+        }
+    }
+}
+
+/// Tallies corruption found while scanning (and optionally repairing) a world.
+#[derive(Debug, Default)]
+pub struct ScanSummary {
+    pub region_files_scanned: usize,
+    pub chunks_examined: usize,
+    pub chunks_corrupt: usize,
+    pub out_of_bounds: usize,
+    pub overlapping: usize,
+    pub size_mismatch: usize,
+    pub decompression_failed: usize,
+    pub missing_tags: usize,
+}
+
+impl ScanSummary {
+    fn record(&mut self, kind: Corruption) {
+        self.chunks_corrupt += 1;
+        match kind {
+            Corruption::OutOfBounds => self.out_of_bounds += 1,
+            Corruption::Overlapping => self.overlapping += 1,
+            Corruption::SizeMismatch => self.size_mismatch += 1,
+            Corruption::DecompressionFailed => self.decompression_failed += 1,
+            Corruption::MissingTags => self.missing_tags += 1,
+        }
+    }
+
+    fn merge(&mut self, other: ScanSummary) {
+        self.region_files_scanned += other.region_files_scanned;
+        self.chunks_examined += other.chunks_examined;
+        self.chunks_corrupt += other.chunks_corrupt;
+        self.out_of_bounds += other.out_of_bounds;
+        self.overlapping += other.overlapping;
+        self.size_mismatch += other.size_mismatch;
+        self.decompression_failed += other.decompression_failed;
+        self.missing_tags += other.missing_tags;
+    }
+
+    pub fn print_report(&self) {
+        println!("Corruption scan summary:");
+        println!("  region files scanned: {}", self.region_files_scanned);
+        println!("  chunks examined:      {}", self.chunks_examined);
+        println!("  chunks corrupt:       {}", self.chunks_corrupt);
+        if self.chunks_corrupt > 0 {
+            println!("    {}: {}", Corruption::OutOfBounds.label(), self.out_of_bounds);
+            println!("    {}: {}", Corruption::Overlapping.label(), self.overlapping);
+            println!("    {}: {}", Corruption::SizeMismatch.label(), self.size_mismatch);
+            println!("    {}: {}", Corruption::DecompressionFailed.label(), self.decompression_failed);
+            println!("    {}: {}", Corruption::MissingTags.label(), self.missing_tags);
+        }
+    }
+}
+
+/// Validates a single chunk entry, returning the compression type and raw payload
+/// bytes if every check passes, or the first `Corruption` reason encountered.
+/// The structural checks (bounds/overlap/size) are shared with the normal
+/// optimisation pass via `region::read_chunk_payload`; only the decompression and
+/// NBT-tag checks are specific to scanning.
+fn validate_chunk(
+    file: &mut File,
+    region_dir: &Path,
+    file_len: u64,
+    location: &ChunkLocation,
+    occupied: &mut Vec<(u32, u32)>,
+) -> Result<(u8, Vec<u8>), Corruption> {
+    let (compression_byte, data) =
+        region::read_chunk_payload(file, region_dir, file_len, location, occupied).map_err(|err| match err {
+            region::ChunkReadError::OutOfBounds => Corruption::OutOfBounds,
+            region::ChunkReadError::Overlapping => Corruption::Overlapping,
+            region::ChunkReadError::SizeMismatch => Corruption::SizeMismatch,
+        })?;
+
+    let decompressed = compression::decompress(compression_byte, &data).map_err(|_| Corruption::DecompressionFailed)?;
+
+    let nbt = read_nbt(&mut Cursor::new(&*decompressed))
+        .map_err(|_| Corruption::MissingTags)?
+        .ok_or(Corruption::MissingTags)?;
+    // `entities`/`poi` chunks carry no InhabitedTime tag; it's only required in `region` folders.
+    let requires_inhabited_time = region_dir.file_name().and_then(|n| n.to_str()) == Some("region");
+    if requires_inhabited_time && nbt.long("InhabitedTime").is_none() {
+        return Err(Corruption::MissingTags);
+    }
+
+    Ok((compression_byte, data))
+}
+
+/// Scans a region file for corrupt chunk entries, optionally repairing it.
+///
+/// In copy mode (`output_path != input_path`) repair rewrites a new region file
+/// containing only the valid chunks. In in-place mode it zeroes the location-table
+/// entry of each corrupt chunk, leaving the rest of the file untouched. With
+/// `dry_run`, every disk write is skipped and only the summary is computed.
+pub fn scan_region_file(input_path: &Path, output_path: &Path, repair: bool, dry_run: bool) -> std::io::Result<ScanSummary> {
+    let mut summary = ScanSummary {
+        region_files_scanned: 1,
+        ..Default::default()
+    };
+
+    let file_name = input_path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let parts: Vec<&str> = file_name.split('.').collect();
+    let (region_x, region_z) = if parts.len() == 4 {
+        (parts[1].parse::<i32>().unwrap_or(0), parts[2].parse::<i32>().unwrap_or(0))
+    } else {
+        (0, 0)
+    };
+    let region_dir = input_path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut file = File::open(input_path)?;
+    let file_len = file.metadata()?.len();
+    if file_len < 2 * SECTOR_SIZE {
+        return Ok(summary);
+    }
+
+    let mut location_table = vec![0u8; SECTOR_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut location_table)?;
+
+    let mut timestamp_table = vec![0u8; SECTOR_SIZE as usize];
+    file.seek(SeekFrom::Start(SECTOR_SIZE))?;
+    file.read_exact(&mut timestamp_table)?;
+
+    let equal_input_output = input_path == output_path;
+    let mut occupied = Vec::new();
+    let mut kept: Vec<(usize, i32, i32, u8, Vec<u8>, i32)> = Vec::new();
+
+    for index in 0..1024 {
+        let loc = i32::from_be_bytes([
+            location_table[index * 4],
+            location_table[index * 4 + 1],
+            location_table[index * 4 + 2],
+            location_table[index * 4 + 3],
+        ]);
+        let num_sectors = (loc & 0xFF) as u32;
+        let sector_offset = (loc >> 8) as u32;
+
+        if sector_offset == 0 && num_sectors == 0 {
+            continue;
+        }
+
+        let chunk_x = region_x * 32 + (index as i32 % 32);
+        let chunk_z = region_z * 32 + (index as i32 / 32);
+
+        summary.chunks_examined += 1;
+        let location = ChunkLocation { chunk_x, chunk_z, sector_offset, num_sectors };
+        match validate_chunk(&mut file, region_dir, file_len, &location, &mut occupied) {
+            Ok((compression_byte, data)) => {
+                let timestamp = i32::from_be_bytes([
+                    timestamp_table[index * 4],
+                    timestamp_table[index * 4 + 1],
+                    timestamp_table[index * 4 + 2],
+                    timestamp_table[index * 4 + 3],
+                ]);
+                kept.push((index, chunk_x, chunk_z, compression_byte, data, timestamp));
+            }
+            Err(kind) => summary.record(kind),
+        }
+    }
+
+    if !repair || dry_run || (equal_input_output && summary.chunks_corrupt == 0) {
+        return Ok(summary);
+    }
+
+    if equal_input_output {
+        let mut file = std::fs::OpenOptions::new().write(true).open(input_path)?;
+        let kept_indices: std::collections::HashSet<usize> = kept.iter().map(|(i, ..)| *i).collect();
+        for index in 0..1024 {
+            if !kept_indices.contains(&index) {
+                let loc = i32::from_be_bytes([
+                    location_table[index * 4],
+                    location_table[index * 4 + 1],
+                    location_table[index * 4 + 2],
+                    location_table[index * 4 + 3],
+                ]);
+                if loc == 0 {
+                    continue;
+                }
+                file.seek(SeekFrom::Start(index as u64 * 4))?;
+                file.write_all(&[0u8; 4])?;
+            }
+        }
+    } else {
+        let output_dir = output_path.parent().unwrap_or_else(|| Path::new("."));
+        let kept_chunks: Vec<KeptChunk> = kept
+            .into_iter()
+            .map(|(index, chunk_x, chunk_z, compression_byte, data, timestamp)| KeptChunk {
+                index,
+                chunk_x,
+                chunk_z,
+                compression_byte,
+                data,
+                timestamp,
+            })
+            .collect();
+        region::write_region_file(output_path, output_dir, &kept_chunks)?;
+    }
+
+    Ok(summary)
+}
+
+/// Runs the scan/repair subsystem over every `.mca` file in `region_dir`.
+pub fn scan_region_directory(region_dir: &str, output_dir: &str, repair: bool, dry_run: bool) -> std::io::Result<ScanSummary> {
+    let mut summary = ScanSummary::default();
+
+    for entry in std::fs::read_dir(region_dir)?.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if !file_name.ends_with(".mca") {
+            continue;
+        }
+
+        let output_path = Path::new(output_dir).join(file_name);
+        match scan_region_file(&path, &output_path, repair, dry_run) {
+            Ok(file_summary) => summary.merge(file_summary),
+            Err(err) => eprintln!("error: failed to scan '{}': {}", path.display(), err),
+        }
+    }
+
+    Ok(summary)
+}
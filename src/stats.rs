@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+/// World-wide optimisation statistics, accumulated concurrently by the Rayon
+/// workers processing each region file.
+#[derive(Default)]
+pub struct OptimisationStats {
+    pub region_files_processed: AtomicU64,
+    pub region_files_emptied: AtomicU64,
+    pub chunks_examined: AtomicU64,
+    pub chunks_deleted: AtomicU64,
+    pub chunks_kept: AtomicU64,
+    pub bytes_reclaimed: AtomicI64,
+    pub duplicate_chunks: AtomicU64,
+    pub duplicate_bytes: AtomicU64,
+    pub recompressed_chunks: AtomicU64,
+    pub recompression_savings: AtomicI64,
+}
+
+impl OptimisationStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn print_report(&self, dry_run: bool) {
+        if dry_run {
+            println!("Optimisation report (dry run, nothing was written):");
+        } else {
+            println!("Optimisation report:");
+        }
+        println!("  region files processed: {}", self.region_files_processed.load(Ordering::Relaxed));
+        println!("  region files emptied:   {}", self.region_files_emptied.load(Ordering::Relaxed));
+        println!("  chunks examined:        {}", self.chunks_examined.load(Ordering::Relaxed));
+        println!("  chunks kept:            {}", self.chunks_kept.load(Ordering::Relaxed));
+        println!("  chunks deleted:         {}", self.chunks_deleted.load(Ordering::Relaxed));
+        println!("  bytes reclaimed:        {}", self.bytes_reclaimed.load(Ordering::Relaxed));
+
+        let duplicate_chunks = self.duplicate_chunks.load(Ordering::Relaxed);
+        if duplicate_chunks > 0 {
+            println!("  duplicate chunks:       {}", duplicate_chunks);
+            println!("  duplicate chunk bytes:  {}", self.duplicate_bytes.load(Ordering::Relaxed));
+        }
+
+        let recompressed_chunks = self.recompressed_chunks.load(Ordering::Relaxed);
+        if recompressed_chunks > 0 {
+            println!("  chunks recompressed:    {}", recompressed_chunks);
+            println!("  recompression savings:  {}", self.recompression_savings.load(Ordering::Relaxed));
+        }
+    }
+}
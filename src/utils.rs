@@ -1,6 +1,10 @@
 use std::fs;
 use std::path::PathBuf;
 
+use crate::dimensions::REGION_BEARING_DIRS;
+
+/// Copies a world directory, skipping every region-bearing folder (`region`,
+/// `entities`, `poi`) at any depth so they can be processed separately.
 pub fn copy_except_region(input_dir: &str, output_dir: &str) -> std::io::Result<()> {
     let mut dirs_to_process = vec![PathBuf::from(input_dir)];
     while let Some(current_dir) = dirs_to_process.pop() {
@@ -28,7 +32,7 @@ pub fn copy_except_region(input_dir: &str, output_dir: &str) -> std::io::Result<
             let path = entry.path();
 
             if path.is_dir() {
-                if path.ends_with("region") {
+                if REGION_BEARING_DIRS.iter().any(|name| path.ends_with(name)) {
                     continue;
                 }
 